@@ -0,0 +1,307 @@
+use actix_web::{
+    dev::ServiceRequest, error::ErrorForbidden, error::ErrorUnauthorized, post, web, Error as ActixError,
+    HttpResponse, ResponseError,
+};
+use actix_web_httpauth::extractors::{basic::BasicAuth, bearer::BearerAuth};
+use derive_more::Display;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+use crate::config::Config;
+
+// The JWT signing secret, shared as app_data so POST /auth/login can reach it. Only mounted when
+// JWT_SECRET is configured.
+pub struct JwtSecret(pub String);
+
+// The claims encoded into a JWT issued by POST /auth/login. `scope` is a space-separated list of
+// granted scopes, e.g. "read write".
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: usize,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Display)]
+pub enum AuthError {
+    #[display(fmt = "CredentialsInvalid")]
+    CredentialsInvalid,
+    #[display(fmt = "TokenInvalid")]
+    TokenInvalid,
+    #[display(fmt = "InsufficientScope")]
+    InsufficientScope,
+}
+
+impl ResponseError for AuthError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            AuthError::CredentialsInvalid => {
+                HttpResponse::Unauthorized().json("{\"error\": \"Invalid Credentials\"}")
+            }
+            AuthError::TokenInvalid => {
+                HttpResponse::Unauthorized().json("{\"error\": \"Invalid or expired token\"}")
+            }
+            AuthError::InsufficientScope => {
+                HttpResponse::Forbidden().json("{\"error\": \"Insufficient scope\"}")
+            }
+        }
+    }
+}
+
+// Validates HTTP Basic credentials against the live config's username/password. Used when
+// JWT_SECRET is unset so existing deployments keep working unchanged. Reads the config fresh on
+// every request so a hot-reloaded credential rotation takes effect without a restart.
+pub async fn validator(
+    req: ServiceRequest,
+    credentials: BasicAuth,
+) -> Result<ServiceRequest, (ActixError, ServiceRequest)> {
+    let (username, password) = match req.app_data::<web::Data<RwLock<Config>>>() {
+        Some(config) => {
+            let config = config.read().unwrap();
+            (config.api_username.clone(), config.api_password.clone())
+        }
+        None => (None, None),
+    };
+
+    match username.is_some() && password.is_some() {
+        true => {
+            let authorised = match username.unwrap() == credentials.user_id() {
+                true => match credentials.password() {
+                    Some(pwd) => pwd == password.unwrap(),
+                    None => false,
+                },
+                false => false,
+            };
+
+            match authorised {
+                true => Ok(req),
+                false => Err((ErrorUnauthorized(AuthError::CredentialsInvalid), req)),
+            }
+        }
+        false => Ok(req),
+    }
+}
+
+// Decodes and validates a bearer JWT (signature + expiry), then checks that its scope claim
+// grants `required_scope`. On success the decoded claims are attached to the request so handlers
+// can read them back out if needed.
+pub async fn bearer_validator(
+    req: ServiceRequest,
+    credentials: BearerAuth,
+    secret: String,
+    required_scope: &'static str,
+) -> Result<ServiceRequest, (ActixError, ServiceRequest)> {
+    let key = DecodingKey::from_secret(secret.as_bytes());
+
+    let claims = match decode::<Claims>(credentials.token(), &key, &Validation::default()) {
+        Ok(data) => data.claims,
+        Err(_) => return Err((ErrorUnauthorized(AuthError::TokenInvalid), req)),
+    };
+
+    let has_scope = claims
+        .scope
+        .as_deref()
+        .map(|scope| scope.split_whitespace().any(|s| s == required_scope))
+        .unwrap_or(false);
+
+    if !has_scope {
+        return Err((ErrorForbidden(AuthError::InsufficientScope), req));
+    }
+
+    req.extensions_mut().insert(claims);
+    Ok(req)
+}
+
+// Signs a JWT for `sub` granting `scope`, expiring after `ttl`.
+pub fn create_token(
+    sub: &str,
+    scope: Option<&str>,
+    secret: &str,
+    ttl: Duration,
+) -> jsonwebtoken::errors::Result<String> {
+    let exp = (SystemTime::now() + ttl)
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before the unix epoch")
+        .as_secs() as usize;
+
+    let claims = Claims {
+        sub: sub.to_string(),
+        exp,
+        scope: scope.map(|s| s.to_string()),
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct LoginRequest {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct LoginResponse {
+    token: String,
+}
+
+// The default lifetime of a token issued by POST /auth/login.
+const TOKEN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Grants "read write" for the primary pair, "read" for the readonly pair, or None if `username`/
+// `password` match neither. Factored out of `login` so this access-control decision is unit
+// testable without going through an HTTP round trip.
+fn scope_for_credentials(
+    username: &str,
+    password: &str,
+    api_username: &Option<String>,
+    api_password: &Option<String>,
+    readonly_username: &Option<String>,
+    readonly_password: &Option<String>,
+) -> Option<&'static str> {
+    let matches = |candidate_username: &Option<String>, candidate_password: &Option<String>| {
+        candidate_username.as_deref() == Some(username) && candidate_password.as_deref() == Some(password)
+    };
+
+    if matches(api_username, api_password) {
+        Some("read write")
+    } else if matches(readonly_username, readonly_password) {
+        Some("read")
+    } else {
+        None
+    }
+}
+
+// Verifies credentials against the primary (read write) or readonly (read) credential pair and
+// returns a JWT granting the matching scope. Only mounted when JWT_SECRET is configured. There is
+// no way to mint a narrower token than these two tiers until the config gains per-client
+// credentials.
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Credentials were valid", body = LoginResponse),
+        (status = 401, description = "Credentials were invalid")
+    )
+)]
+#[post("/auth/login")]
+pub async fn login(
+    payload: web::Json<LoginRequest>,
+    config: web::Data<RwLock<Config>>,
+    jwt_secret: web::Data<JwtSecret>,
+) -> HttpResponse {
+    let (username, password, readonly_username, readonly_password) = {
+        let config = config.read().unwrap();
+        (
+            config.api_username.clone(),
+            config.api_password.clone(),
+            config.readonly_username.clone(),
+            config.readonly_password.clone(),
+        )
+    };
+
+    let scope = match scope_for_credentials(
+        &payload.username,
+        &payload.password,
+        &username,
+        &password,
+        &readonly_username,
+        &readonly_password,
+    ) {
+        Some(scope) => scope,
+        None => return AuthError::CredentialsInvalid.error_response(),
+    };
+
+    match create_token(&payload.username, Some(scope), &jwt_secret.0, TOKEN_TTL) {
+        Ok(token) => HttpResponse::Ok().json(LoginResponse { token }),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{dev::Payload, http::header, test::TestRequest, FromRequest};
+
+    fn pair(username: &str, password: &str) -> (Option<String>, Option<String>) {
+        (Some(username.to_string()), Some(password.to_string()))
+    }
+
+    #[test]
+    fn primary_credentials_grant_read_write() {
+        let (api_username, api_password) = pair("admin", "hunter2");
+        let (readonly_username, readonly_password) = pair("viewer", "letmein");
+
+        let scope = scope_for_credentials(
+            "admin",
+            "hunter2",
+            &api_username,
+            &api_password,
+            &readonly_username,
+            &readonly_password,
+        );
+
+        assert_eq!(scope, Some("read write"));
+    }
+
+    #[test]
+    fn readonly_credentials_grant_read_only() {
+        let (api_username, api_password) = pair("admin", "hunter2");
+        let (readonly_username, readonly_password) = pair("viewer", "letmein");
+
+        let scope = scope_for_credentials(
+            "viewer",
+            "letmein",
+            &api_username,
+            &api_password,
+            &readonly_username,
+            &readonly_password,
+        );
+
+        assert_eq!(scope, Some("read"));
+    }
+
+    #[test]
+    fn wrong_credentials_grant_no_scope() {
+        let (api_username, api_password) = pair("admin", "hunter2");
+        let (readonly_username, readonly_password) = pair("viewer", "letmein");
+
+        let scope = scope_for_credentials(
+            "admin",
+            "wrong-password",
+            &api_username,
+            &api_password,
+            &readonly_username,
+            &readonly_password,
+        );
+
+        assert_eq!(scope, None);
+    }
+
+    #[actix_web::test]
+    async fn bearer_validator_rejects_read_scope_for_write_requirement() {
+        let secret = "test-secret";
+        let token = create_token("viewer", Some("read"), secret, Duration::from_secs(60)).unwrap();
+
+        let http_req = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_http_request();
+        let mut payload = Payload::None;
+        let credentials = BearerAuth::from_request(&http_req, &mut payload)
+            .await
+            .unwrap();
+
+        let srv_req = TestRequest::default().to_srv_request();
+
+        let result = bearer_validator(srv_req, credentials, secret.to_string(), "write").await;
+
+        assert!(result.is_err());
+    }
+}
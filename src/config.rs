@@ -0,0 +1,158 @@
+use actix_web::web;
+use log::{error, info, warn};
+use serde::Deserialize;
+use std::{
+    env, fs,
+    sync::RwLock,
+    time::{Duration, SystemTime},
+};
+
+fn default_page_size() -> usize {
+    50
+}
+
+fn default_bind_address() -> String {
+    "0.0.0.0:8080".to_string()
+}
+
+fn default_cors_allowed_methods() -> Vec<String> {
+    vec!["GET".to_string(), "POST".to_string()]
+}
+
+fn default_cors_allowed_headers() -> Vec<String> {
+    vec!["content-type".to_string(), "authorization".to_string()]
+}
+
+// Parses a comma-separated env var into a list, dropping blank entries. Used for the CORS
+// settings below so they can be set without a config file, consistent with the other fields.
+fn env_list(key: &str) -> Option<Vec<String>> {
+    env::var(key).ok().map(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+// Operator-tunable settings. Loaded from the TOML file at CONFIG_PATH when set, otherwise from
+// the pre-existing environment variables so deployments that don't opt into a config file keep
+// working unchanged. When CONFIG_PATH is set, main spawns a background task that reloads this
+// struct in place whenever the file's mtime changes, so operators can retune paging or rotate
+// credentials without a restart.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub api_username: Option<String>,
+    pub api_password: Option<String>,
+    // A second, optional credential pair that POST /auth/login grants "read" scope only (the
+    // primary pair above grants "read write"). Unset by default: deployments that don't configure
+    // it simply have no way to mint a read-only token.
+    pub readonly_username: Option<String>,
+    pub readonly_password: Option<String>,
+    pub connection_string: Option<String>,
+    #[serde(default = "default_page_size")]
+    pub page_size: usize,
+    #[serde(default = "default_bind_address")]
+    pub bind_address: String,
+    // Origins allowed to make cross-origin requests. Empty (the default) means no cross-origin
+    // access at all, matching this API's behaviour before CORS support existed. This is patient
+    // data behind a bearer token, so allowing any origin is an explicit opt-in: list "*" here,
+    // don't rely on leaving the list empty.
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_allowed_methods")]
+    pub cors_allowed_methods: Vec<String>,
+    #[serde(default = "default_cors_allowed_headers")]
+    pub cors_allowed_headers: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            api_username: env::var("API_USERNAME").ok(),
+            api_password: env::var("API_PASSWORD").ok(),
+            readonly_username: env::var("READONLY_USERNAME").ok(),
+            readonly_password: env::var("READONLY_PASSWORD").ok(),
+            connection_string: env::var("CONNECTION_STRING").ok(),
+            page_size: default_page_size(),
+            bind_address: default_bind_address(),
+            cors_allowed_origins: env_list("CORS_ALLOWED_ORIGINS").unwrap_or_default(),
+            cors_allowed_methods: env_list("CORS_ALLOWED_METHODS")
+                .unwrap_or_else(default_cors_allowed_methods),
+            cors_allowed_headers: env_list("CORS_ALLOWED_HEADERS")
+                .unwrap_or_else(default_cors_allowed_headers),
+        }
+    }
+}
+
+// Loads the config from CONFIG_PATH, or falls back to Config::default() (environment variables)
+// if CONFIG_PATH is unset or the file can't be read/parsed.
+pub fn load() -> Config {
+    match env::var("CONFIG_PATH") {
+        Ok(path) => match load_from_file(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                error!(
+                    "Failed to load config from {}: {}. Falling back to environment variables.",
+                    path, e
+                );
+                Config::default()
+            }
+        },
+        Err(_) => Config::default(),
+    }
+}
+
+pub fn load_from_file(path: &str) -> Result<Config, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)?;
+    let mut config: Config = toml::from_str(&contents)?;
+
+    // A page_size of 0 here (a typo, or an operator trying to "disable" paging) would make every
+    // GET /readings call return an empty page with no next_cursor. Clamp it up to 1 instead.
+    if config.page_size == 0 {
+        warn!(
+            "page_size must be at least 1 (got 0) in {}; clamping to 1",
+            path
+        );
+        config.page_size = 1;
+    }
+
+    Ok(config)
+}
+
+// Polls `path`'s mtime every `interval` and reloads `config` in place when it changes. Spawned
+// once at startup when CONFIG_PATH is set; runs for the lifetime of the process.
+pub fn spawn_watcher(config: web::Data<RwLock<Config>>, path: String, interval: Duration) {
+    tokio::spawn(async move {
+        let mut last_modified = file_mtime(&path);
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let modified = file_mtime(&path);
+            if modified.is_none() || modified == last_modified {
+                continue;
+            }
+
+            match load_from_file(&path) {
+                Ok(reloaded) => {
+                    *config.write().unwrap() = reloaded;
+                    last_modified = modified;
+                    info!("Reloaded config from {}", path);
+                }
+                Err(e) => error!("Failed to reload config from {}: {}", path, e),
+            }
+        }
+    });
+}
+
+fn file_mtime(path: &str) -> Option<SystemTime> {
+    match fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(modified) => Some(modified),
+        Err(e) => {
+            warn!("Could not stat config file {}: {}", path, e);
+            None
+        }
+    }
+}
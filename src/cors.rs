@@ -0,0 +1,26 @@
+use actix_cors::Cors;
+
+use crate::config::Config;
+
+// Builds the CORS layer from the operator-configured allow-list. Read once at startup (CORS
+// settings aren't hot-reloaded like page_size, since the middleware is built per-worker when the
+// HttpServer closure runs) rather than from the live, hot-reloadable config.
+//
+// Fails closed: an empty allow-list leaves origin unconfigured, so Cors rejects every
+// cross-origin request (matching behaviour before CORS support existed). Any-origin access is an
+// explicit opt-in via a literal "*" entry, never the default for an empty list — this API serves
+// patient data behind a bearer token, so the list being forgotten must not silently widen access.
+pub fn build(config: &Config) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods(config.cors_allowed_methods.iter().map(String::as_str))
+        .allowed_headers(config.cors_allowed_headers.iter().map(String::as_str));
+
+    if config.cors_allowed_origins.iter().any(|origin| origin == "*") {
+        cors.allow_any_origin()
+    } else {
+        config
+            .cors_allowed_origins
+            .iter()
+            .fold(cors, |cors, origin| cors.allowed_origin(origin))
+    }
+}
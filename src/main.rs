@@ -1,13 +1,10 @@
 use actix_web::{
-    dev::ServiceRequest,
-    get,
-    post, web, App, HttpResponse, HttpServer, ResponseError,
-};
-use actix_web_httpauth::{
-    extractors::basic::BasicAuth,
-    middleware::HttpAuthentication,
+    guard, get,
+    middleware::Compress,
+    post, web, App, HttpResponse, HttpServer,
 };
-use derive_more::Display;
+use actix_web_httpauth::middleware::HttpAuthentication;
+use base64::{engine::general_purpose, Engine as _};
 use dotenv::dotenv;
 use futures::stream::StreamExt;
 
@@ -21,14 +18,28 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
     env,
+    time::Duration,
 };
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+mod auth;
+mod config;
+mod cors;
+mod metrics;
+mod openapi;
+mod stream;
 
-// The amount of readings returned per page.
-const PAGE_SIZE: usize = 50;
+use config::Config;
+use std::sync::RwLock;
 
 // A reading from the database.
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
 struct DBReading {
+    // Omitted on insert so MongoDB assigns it; populated on read. Doubles as the tiebreaker for
+    // keyset pagination when several readings share a `reading_at`.
+    #[serde(rename = "_id", default, skip_serializing_if = "Option::is_none")]
+    id: Option<bson::oid::ObjectId>,
     reading_at: bson::DateTime,
     data: Vec<ContinuousData>,
     created_at: bson::DateTime,
@@ -36,7 +47,7 @@ struct DBReading {
 }
 
 // A format to serialize the incoming JSON payload from the POST request
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
 struct NewReading {
     #[serde(with = "bson_datetime_as_rfc3339_string")]
     reading_at: bson::DateTime,
@@ -45,7 +56,7 @@ struct NewReading {
 }
 
 // Represents a reading of data from a continuous capture from the HoloLens.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 struct ContinuousData {
     service_id: String,
     alias: Option<String>,
@@ -55,7 +66,7 @@ struct ContinuousData {
 
 // Represents the patient data attached to each reading. Supports arbitrary patient data under the data
 // key.
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
 struct Patient {
     bluetooth_id: String,
     alias: Option<String>,
@@ -63,13 +74,20 @@ struct Patient {
 }
 
 // An endpoint to see if the API is active.
+#[utoipa::path(
+    get,
+    path = "/status",
+    responses(
+        (status = 200, description = "The API is active", body = String)
+    )
+)]
 #[get("/status")]
 async fn get_status(_client: web::Data<Client>) -> HttpResponse {
     HttpResponse::Ok().body("Hi")
 }
 
 // The optional parameters to the GET /readings request.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, IntoParams)]
 struct ReadingsQueryParam {
     // If key exists, API will only return readings from this bluetooth id.
     patient: Option<String>,
@@ -80,23 +98,93 @@ struct ReadingsQueryParam {
     // Specifies a latest datetime (URL Encoded RFC3339) for the reading data
     until: Option<String>,
 
-    // Specifies which page number to return. Readings are returned in blocks of PAGE_SIZE.
+    // If key exists, API will only return readings whose data contains this service_id.
+    service_id: Option<String>,
+
+    // Deprecated: skip-based page number. Readings are returned in blocks of the configured
+    // page_size. Prefer `after`, which doesn't degrade on large collections. Kept working for
+    // one release.
     page: Option<u64>,
+
+    // Opaque cursor from a previous response's `next_cursor`. Returns the page of readings
+    // strictly after it, ordered by reading_at. Preferred over `page`.
+    after: Option<String>,
 }
 
-// The format of the JSON respone to the GET /readings request.
+// An opaque (reading_at, _id) keyset cursor, base64-encoded for transport in `after`/`next_cursor`.
 #[derive(Serialize, Deserialize)]
+struct ReadingsCursor {
+    reading_at: bson::DateTime,
+    id: bson::oid::ObjectId,
+}
+
+// Encodes the cursor a client should pass as `after` to resume just past `reading`.
+fn encode_cursor(reading: &DBReading) -> Option<String> {
+    let cursor = ReadingsCursor {
+        reading_at: reading.reading_at,
+        id: reading.id?,
+    };
+    let json = serde_json::to_vec(&cursor).ok()?;
+    Some(general_purpose::URL_SAFE_NO_PAD.encode(json))
+}
+
+// Decodes an `after` cursor produced by encode_cursor. Returns None if it's malformed.
+fn decode_cursor(raw: &str) -> Option<ReadingsCursor> {
+    let bytes = general_purpose::URL_SAFE_NO_PAD.decode(raw).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+// Builds a single reading_at subdocument merging $gte (from) and $lt (until) so supplying both
+// narrows the range instead of the until branch silently overwriting the from branch. Returns
+// None if neither bound was supplied.
+fn build_reading_at_filter(
+    from: Option<bson::DateTime>,
+    until: Option<bson::DateTime>,
+) -> Option<bson::Document> {
+    if from.is_none() && until.is_none() {
+        return None;
+    }
+
+    let mut reading_at_range = bson::Document::new();
+    if let Some(from) = from {
+        reading_at_range.insert("$gte", from);
+    }
+    if let Some(until) = until {
+        reading_at_range.insert("$lt", until);
+    }
+    Some(reading_at_range)
+}
+
+// The format of the JSON respone to the GET /readings request.
+#[derive(Serialize, Deserialize, ToSchema)]
 struct GetReadingsResponse {
     readings: Vec<DBReading>,
+
+    // The cursor to pass as `after` to fetch the next page, or null if this page was the last.
+    next_cursor: Option<String>,
 }
 
 // An API endpoint that returns the readings in the database. Can have query paramters:
 // patient (bluetooth_id), from, until, page.
+#[utoipa::path(
+    get,
+    path = "/readings",
+    params(ReadingsQueryParam),
+    responses(
+        (status = 200, description = "The matching readings", body = GetReadingsResponse),
+        (status = 400, description = "A from/until date was not valid RFC3339, from was after until, or the after cursor was malformed"),
+        (status = 500, description = "The database query failed")
+    )
+)]
 #[get("/readings")]
 async fn get_readings(
     client: web::Data<Client>,
     query: web::Query<ReadingsQueryParam>,
+    config: web::Data<RwLock<Config>>,
+    metrics: web::Data<metrics::Metrics>,
 ) -> HttpResponse {
+    let page_size = config.read().unwrap().page_size;
+
     let readings_collection = client.database("cfa-hud").collection("readings");
 
     let mut filter_options = bson::Document::new();
@@ -105,38 +193,77 @@ async fn get_readings(
         filter_options.insert("patient.bluetooth_id", bid);
     };
 
-    // Adds filter for readings after the from date. from string must be in URL Encoded RFC3339
-    // format.
+    // If service_id specified, adds a filter for only readings with a matching continuous-data
+    // service_id.
+    if let Some(service_id) = &query.service_id {
+        filter_options.insert("data.service_id", service_id);
+    };
+
+    let mut from_date = None;
+    let mut until_date = None;
+
+    // from string must be in URL Encoded RFC3339 format.
     if let Some(from) = &query.from {
-        if let Ok(date) = bson::DateTime::parse_rfc3339_str(from) {
-            filter_options.insert("reading_at", doc!("$gte": date));
-        } else {
-            return HttpResponse::BadRequest().body("from date is invalid");
+        match bson::DateTime::parse_rfc3339_str(from) {
+            Ok(date) => from_date = Some(date),
+            Err(_) => return HttpResponse::BadRequest().body("from date is invalid"),
         }
     };
 
-    // Adds filter for readings before the until date. until string must be in URL Encoded RFC3339
-    // format.
+    // until string must be in URL Encoded RFC3339 format.
     if let Some(until) = &query.until {
-        if let Ok(date) = bson::DateTime::parse_rfc3339_str(until) {
-            filter_options.insert("reading_at", doc!("$lt": date));
-        } else {
-            return HttpResponse::BadRequest().body("from date is invalid");
+        match bson::DateTime::parse_rfc3339_str(until) {
+            Ok(date) => until_date = Some(date),
+            Err(_) => return HttpResponse::BadRequest().body("until date is invalid"),
         }
     };
 
+    if let (Some(from), Some(until)) = (from_date, until_date) {
+        if from > until {
+            return HttpResponse::BadRequest().body("from date must not be after until date");
+        }
+    }
+
+    if let Some(reading_at_range) = build_reading_at_filter(from_date, until_date) {
+        filter_options.insert("reading_at", reading_at_range);
+    }
+
+    // Keyset pagination: decode `after` into the last-seen (reading_at, _id) and restrict to
+    // strictly-after documents. Combined with the other filters via $and so it composes with
+    // patient/from/until instead of overwriting them.
+    let cursor_filter = match &query.after {
+        Some(after) => match decode_cursor(after) {
+            Some(cursor) => Some(doc! {
+                "$or": [
+                    { "reading_at": { "$gt": cursor.reading_at } },
+                    { "reading_at": cursor.reading_at, "_id": { "$gt": cursor.id } },
+                ]
+            }),
+            None => return HttpResponse::BadRequest().body("after cursor is invalid"),
+        },
+        None => None,
+    };
+
+    let filter = match cursor_filter {
+        Some(cursor_filter) => doc! { "$and": [filter_options, cursor_filter] },
+        None => filter_options,
+    };
+
+    // Fetches one reading past page_size so a full final page can be told apart from a page with
+    // more readings after it.
     let find_options_builder = mongodb::options::FindOptions::builder()
-        .limit(Some(PAGE_SIZE.try_into().unwrap()))
-        .batch_size(Some(PAGE_SIZE.try_into().unwrap()));
+        .limit(Some((page_size + 1).try_into().unwrap()))
+        .batch_size(Some((page_size + 1).try_into().unwrap()))
+        .sort(doc! { "reading_at": 1, "_id": 1 });
 
-    // If page numbers, specified returns readings page * PAGE_SIZE to page + 1 * PAGE_SIZE, if
-    // they exist.
+    // If page numbers, specified returns readings page * page_size to page + 1 * page_size, if
+    // they exist. Deprecated in favour of `after`.
     let find_options = match &query.page {
-        Some(page) => find_options_builder.skip(page * PAGE_SIZE as u64).build(),
+        Some(page) => find_options_builder.skip(page * page_size as u64).build(),
         None => find_options_builder.build(),
     };
 
-    let cursor = readings_collection.find(filter_options, find_options).await;
+    let cursor = readings_collection.find(filter, find_options).await;
 
     match cursor {
         Ok(mut c) => {
@@ -146,28 +273,63 @@ async fn get_readings(
             while let Some(result) = c.next().await {
                 match result {
                     Ok(doc) => readings.push(doc),
-                    Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+                    Err(e) => {
+                        metrics
+                            .mongo_errors_total
+                            .with_label_values(&["/readings"])
+                            .inc();
+                        return HttpResponse::InternalServerError().body(e.to_string());
+                    }
                 }
             }
 
+            // The extra reading past page_size means there is more to fetch; drop it and hand back
+            // a cursor for the next page.
+            let next_cursor = if readings.len() > page_size {
+                readings.truncate(page_size);
+                readings.last().and_then(encode_cursor)
+            } else {
+                None
+            };
+
             // Returns a 200 OK response with the readings
-            HttpResponse::Ok().json(web::Json(GetReadingsResponse { readings: readings }))
+            HttpResponse::Ok().json(web::Json(GetReadingsResponse {
+                readings,
+                next_cursor,
+            }))
+        }
+        Err(e) => {
+            metrics
+                .mongo_errors_total
+                .with_label_values(&["/readings"])
+                .inc();
+            HttpResponse::InternalServerError().body(e.to_string())
         }
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 struct PostReadingsPayload {
     readings: Vec<NewReading>,
 }
 
 // An API endpoint to add readings to the database. Readings have to be in a specific format given
 // by the NewReading struct.
+#[utoipa::path(
+    post,
+    path = "/readings",
+    request_body = PostReadingsPayload,
+    responses(
+        (status = 200, description = "The readings were inserted"),
+        (status = 500, description = "The database insert failed")
+    )
+)]
 #[post("/readings")]
 async fn post_readings(
     client: web::Data<Client>,
     payload: web::Json<PostReadingsPayload>,
+    metrics: web::Data<metrics::Metrics>,
+    broadcaster: web::Data<stream::ReadingsBroadcaster>,
 ) -> HttpResponse {
     let readings_collection = client
         .database("cfa-hud")
@@ -179,11 +341,32 @@ async fn post_readings(
         .map(|x| convert_to_db_reading(x))
         .collect::<Vec<_>>();
 
+    let inserted_count = new_readings.len() as u64;
+    let to_broadcast = new_readings.clone();
+
     let result = readings_collection.insert_many(new_readings, None).await;
 
     match result {
-        Ok(_) => HttpResponse::Ok().body("200 OK"),
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+        Ok(insert_result) => {
+            metrics.readings_inserted_total.inc_by(inserted_count);
+
+            // Best-effort: nobody has to be listening on /readings/stream for this to succeed.
+            for (index, mut reading) in to_broadcast.into_iter().enumerate() {
+                if let Some(bson::Bson::ObjectId(oid)) = insert_result.inserted_ids.get(&index) {
+                    reading.id = Some(*oid);
+                }
+                let _ = broadcaster.send(reading);
+            }
+
+            HttpResponse::Ok().body("200 OK")
+        }
+        Err(e) => {
+            metrics
+                .mongo_errors_total
+                .with_label_values(&["/readings"])
+                .inc();
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
     }
 }
 
@@ -192,6 +375,7 @@ fn convert_to_db_reading(reading: &NewReading) -> DBReading {
     let cloned = reading.clone();
 
     DBReading {
+        id: None,
         reading_at: cloned.reading_at,
         data: cloned.data,
         patient: cloned.patient,
@@ -216,84 +400,57 @@ async fn connect_mongodb(connection_string: String) -> mongodb::error::Result<Cl
     Ok(client)
 }
 
-async fn validator(
-    req: ServiceRequest,
-    credentials: BasicAuth,
-    username: Option<String>,
-    password: Option<String>,
-) -> Result<ServiceRequest, (actix_web::Error, ServiceRequest)> {
-    match username.is_some() && password.is_some() {
-        true => {
-            let authorised = match username.unwrap() == credentials.user_id() {
-                true => match credentials.password() {
-                    Some(pwd) => pwd == password.unwrap(),
-                    None => false,
-                },
-                false => false,
-            };
-
-            match authorised {
-                true => Ok(req),
-                false => Err((
-                    actix_web::error::ErrorUnauthorized(AuthError::CredentialsInvalid),
-                    req,
-                )),
-            }
-        }
-        false => Ok(req),
-    }
-}
-
-#[derive(Debug, Display)]
-enum AuthError {
-    #[display(fmt = "CredentialsInvalid")]
-    CredentialsInvalid,
-}
-
-impl ResponseError for AuthError {
-    fn error_response(&self) -> HttpResponse {
-        match self {
-            AuthError::CredentialsInvalid => {
-                HttpResponse::Unauthorized().json("{\"error\": \"Invalid Credentials\"}")
-            }
-        }
-    }
-}
-
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
 
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
-    let username = match env::var("API_USERNAME") {
-        Err(_) => None,
-        Ok(username) => {
-            info!("Username logged");
-            Some(username)
-        }
-    };
-    let password = match env::var("API_PASSWORD") {
-        Err(_) => None,
-        Ok(pwd) => {
-            info!("Password logged.");
-            Some(pwd)
-        }
-    };
+    // Loads config from CONFIG_PATH if set, falling back to the legacy environment variables
+    // otherwise. When CONFIG_PATH is set, a background task reloads it in place on change so
+    // operators can retune paging or rotate credentials without downtime.
+    let config_path = env::var("CONFIG_PATH").ok();
+    let config = config::load();
 
-    if username.is_none() || password.is_none() {
-        warn!("Username or password missing in environment. Starting unauthenticated API");
+    if config.api_username.is_none() || config.api_password.is_none() {
+        warn!("Username or password missing. Starting unauthenticated API unless JWT_SECRET is set");
     } else {
-        info!("Username and password logged. Starting authenticated API");
+        info!("Username and password configured. Starting authenticated API");
     }
 
-    // Loads the connection string from the environment variables.
-    let client = match env::var("CONNECTION_STRING") {
-        Err(_) => {
+    let connection_string = config.connection_string.clone();
+    let bind_address = config.bind_address.clone();
+    // CORS isn't hot-reloaded (the middleware is built once per worker at startup), so take a
+    // snapshot now rather than reading it back out of config_data later.
+    let cors_config = config.clone();
+
+    // JWT_SECRET, when set, switches the API from HTTP Basic auth to bearer-token auth scoped
+    // per-route (read/write). Basic auth remains the fallback so existing deployments that only
+    // configure credentials keep working unchanged.
+    let jwt_secret = env::var("JWT_SECRET").ok();
+    if jwt_secret.is_some() {
+        info!("JWT_SECRET set. Starting API with bearer-token auth.");
+    }
+
+    let config_data = web::Data::new(RwLock::new(config));
+
+    if let Some(path) = config_path {
+        let reload_interval = env::var("CONFIG_RELOAD_INTERVAL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(30));
+
+        config::spawn_watcher(config_data.clone(), path, reload_interval);
+    }
+
+    // Loads the connection string from the config.
+    let client = match connection_string {
+        None => {
             error!("No connection string");
             None
         }
-        Ok(s) => match connect_mongodb(s).await {
+        Some(s) => match connect_mongodb(s).await {
             Err(_) => {
                 error!("Couldn't complete database connection test.");
                 None
@@ -305,27 +462,129 @@ async fn main() -> std::io::Result<()> {
         },
     };
 
-    let auth_closure = |username: Option<String>, password: Option<String>| move |req: ServiceRequest, credentials: BasicAuth| validator(req, credentials, username.clone(), password.clone());
-    // Starts the webserver if the app successfully connected to the DB.
-    let current_auth_closure = auth_closure(username, password);
-
     match client {
         None => {
             error!("No client could be established.");
             Ok(())
         }
         Some(c) => {
-            HttpServer::new(move || {
-                App::new()
-                    .wrap(HttpAuthentication::basic(current_auth_closure.clone()))
-                    .app_data(web::Data::new(c.clone()))
-                    .service(get_status)
-                    .service(get_readings)
-                    .service(post_readings)
-            })
-            .bind(("0.0.0.0", 8080))?
-            .run()
-            .await
+            let openapi = openapi::ApiDoc::openapi();
+            let metrics_data = web::Data::new(metrics::Metrics::new());
+            let broadcaster_data = web::Data::new(stream::new_broadcaster());
+
+            match jwt_secret {
+                Some(secret) => {
+                    let jwt_secret_data = web::Data::new(auth::JwtSecret(secret.clone()));
+
+                    HttpServer::new(move || {
+                        let read_secret = secret.clone();
+                        let write_secret = secret.clone();
+
+                        App::new()
+                            .wrap(Compress::default())
+                            .wrap(metrics::RequestMetrics {
+                                metrics: metrics_data.clone(),
+                            })
+                            .wrap(cors::build(&cors_config))
+                            .app_data(web::Data::new(c.clone()))
+                            .app_data(config_data.clone())
+                            .app_data(jwt_secret_data.clone())
+                            .app_data(metrics_data.clone())
+                            .app_data(broadcaster_data.clone())
+                            .service(get_status)
+                            .service(auth::login)
+                            .service(metrics::metrics_endpoint)
+                            .service(
+                                SwaggerUi::new("/docs/{_:.*}")
+                                    .url("/api-docs/openapi.json", openapi.clone()),
+                            )
+                            .service(
+                                // An unprefixed scope so the guard/auth wrap can apply to the
+                                // read-scoped routes without doubling their paths
+                                // (get_readings/get_readings_stream already carry their own
+                                // absolute paths from their macros). An empty-prefix scope takes
+                                // every GET that reaches it, so the public GET services are all
+                                // registered above it.
+                                web::scope("")
+                                    .guard(guard::Get())
+                                    .wrap(HttpAuthentication::bearer(move |req, credentials| {
+                                        auth::bearer_validator(req, credentials, read_secret.clone(), "read")
+                                    }))
+                                    .service(get_readings)
+                                    .service(stream::get_readings_stream),
+                            )
+                            .service(
+                                web::scope("")
+                                    .guard(guard::Post())
+                                    .wrap(HttpAuthentication::bearer(move |req, credentials| {
+                                        auth::bearer_validator(req, credentials, write_secret.clone(), "write")
+                                    }))
+                                    .service(post_readings),
+                            )
+                    })
+                    .bind(bind_address)?
+                    .run()
+                    .await
+                }
+                None => {
+                    HttpServer::new(move || {
+                        App::new()
+                            .wrap(Compress::default())
+                            .wrap(metrics::RequestMetrics {
+                                metrics: metrics_data.clone(),
+                            })
+                            .wrap(HttpAuthentication::basic(auth::validator))
+                            .wrap(cors::build(&cors_config))
+                            .app_data(web::Data::new(c.clone()))
+                            .app_data(config_data.clone())
+                            .app_data(metrics_data.clone())
+                            .app_data(broadcaster_data.clone())
+                            .service(get_status)
+                            .service(get_readings)
+                            .service(post_readings)
+                            .service(stream::get_readings_stream)
+                            .service(metrics::metrics_endpoint)
+                            .service(
+                                SwaggerUi::new("/docs/{_:.*}")
+                                    .url("/api-docs/openapi.json", openapi.clone()),
+                            )
+                    })
+                    .bind(bind_address)?
+                    .run()
+                    .await
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_reading_at_filter_keeps_both_bounds() {
+        let from = bson::DateTime::parse_rfc3339_str("2024-01-01T00:00:00Z").unwrap();
+        let until = bson::DateTime::parse_rfc3339_str("2024-02-01T00:00:00Z").unwrap();
+
+        let filter = build_reading_at_filter(Some(from), Some(until)).unwrap();
+
+        assert_eq!(filter.get_datetime("$gte").unwrap(), &from);
+        assert_eq!(filter.get_datetime("$lt").unwrap(), &until);
+    }
+
+    #[test]
+    fn build_reading_at_filter_from_only() {
+        let from = bson::DateTime::parse_rfc3339_str("2024-01-01T00:00:00Z").unwrap();
+
+        let filter = build_reading_at_filter(Some(from), None).unwrap();
+
+        assert_eq!(filter.get_datetime("$gte").unwrap(), &from);
+        assert!(filter.get("$lt").is_none());
+    }
+
+    #[test]
+    fn build_reading_at_filter_none_when_no_bounds() {
+        assert!(build_reading_at_filter(None, None).is_none());
+    }
+}
@@ -0,0 +1,167 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    get, web, Error, HttpResponse,
+};
+use futures::future::LocalBoxFuture;
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder};
+use std::{
+    future::{ready, Ready},
+    time::Instant,
+};
+
+// The Prometheus collectors this service exposes, plus the registry they're gathered from.
+// Shared as app_data so both the timing middleware and the handlers it instruments
+// (get_readings, post_readings) record against the same collectors.
+pub struct Metrics {
+    registry: Registry,
+    pub requests_total: IntCounterVec,
+    pub request_duration_seconds: HistogramVec,
+    pub readings_inserted_total: IntCounter,
+    pub mongo_errors_total: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let registry = Registry::new();
+
+        let requests_total = IntCounterVec::new(
+            Opts::new("http_requests_total", "Total HTTP requests by route and status"),
+            &["route", "status"],
+        )
+        .unwrap();
+
+        let request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency in seconds by route",
+            ),
+            &["route"],
+        )
+        .unwrap();
+
+        let readings_inserted_total = IntCounter::new(
+            "readings_inserted_total",
+            "Total readings inserted via POST /readings",
+        )
+        .unwrap();
+
+        let mongo_errors_total = IntCounterVec::new(
+            Opts::new("mongo_errors_total", "Total MongoDB query errors by route"),
+            &["route"],
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(requests_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(request_duration_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(readings_inserted_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(mongo_errors_total.clone()))
+            .unwrap();
+
+        Metrics {
+            registry,
+            requests_total,
+            request_duration_seconds,
+            readings_inserted_total,
+            mongo_errors_total,
+        }
+    }
+}
+
+// Serves the collected metrics in Prometheus text format.
+#[get("/metrics")]
+pub async fn metrics_endpoint(metrics: web::Data<Metrics>) -> HttpResponse {
+    let encoder = TextEncoder::new();
+    let metric_families = metrics.registry.gather();
+
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&metric_families, &mut buffer) {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+
+    HttpResponse::Ok()
+        .content_type(encoder.format_type())
+        .body(buffer)
+}
+
+// Actix middleware that times every request and records it against `requests_total` (by route
+// and status) and `request_duration_seconds` (by route).
+pub struct RequestMetrics {
+    pub metrics: web::Data<Metrics>,
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware {
+            service,
+            metrics: self.metrics.clone(),
+        }))
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: S,
+    metrics: web::Data<Metrics>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let metrics = self.metrics.clone();
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| req.path().to_string());
+        let start = Instant::now();
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await;
+
+            // An error from an inner service (e.g. a rejected bearer token) still reaches the
+            // client as a response, so it is recorded under the status it renders as.
+            let status = match &res {
+                Ok(res) => res.status(),
+                Err(e) => e.as_response_error().status_code(),
+            };
+            let status = status.as_u16().to_string();
+            metrics
+                .requests_total
+                .with_label_values(&[&route, &status])
+                .inc();
+            metrics
+                .request_duration_seconds
+                .with_label_values(&[&route])
+                .observe(start.elapsed().as_secs_f64());
+
+            res
+        })
+    }
+}
@@ -0,0 +1,30 @@
+use utoipa::OpenApi;
+
+use crate::{
+    auth::{login, LoginRequest, LoginResponse},
+    get_readings, get_status, post_readings, ContinuousData, DBReading, GetReadingsResponse,
+    NewReading, Patient, PostReadingsPayload,
+};
+use crate::stream::get_readings_stream;
+
+// The top-level OpenAPI document for the CFA HUD readings API. Served as raw JSON at
+// /api-docs/openapi.json and rendered interactively at /docs.
+//
+// Query param structs (ReadingsQueryParam, StreamQueryParam) derive IntoParams and are declared
+// via `params(...)` on their handler's #[utoipa::path], not listed here: `schemas()` is for
+// request/response bodies (ToSchema), not query parameters.
+#[derive(OpenApi)]
+#[openapi(
+    paths(get_status, get_readings, post_readings, login, get_readings_stream),
+    components(schemas(
+        DBReading,
+        NewReading,
+        ContinuousData,
+        Patient,
+        GetReadingsResponse,
+        PostReadingsPayload,
+        LoginRequest,
+        LoginResponse,
+    ))
+)]
+pub struct ApiDoc;
@@ -0,0 +1,77 @@
+use actix_web::{get, http::header, web, HttpResponse};
+use futures::stream;
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use utoipa::IntoParams;
+
+use crate::DBReading;
+
+// Fans newly-inserted readings out to subscribed SSE clients. Fed from post_readings; readings
+// are dropped if nobody is currently subscribed. Sized generously so a slow client lags rather
+// than stalling the broadcaster for everyone else.
+pub type ReadingsBroadcaster = broadcast::Sender<DBReading>;
+
+pub fn new_broadcaster() -> ReadingsBroadcaster {
+    let (tx, _rx) = broadcast::channel(1024);
+    tx
+}
+
+// The optional parameters to the GET /readings/stream request.
+#[derive(Deserialize, IntoParams)]
+pub struct StreamQueryParam {
+    // If key exists, only readings for this bluetooth id are streamed.
+    patient: Option<String>,
+}
+
+// Streams newly-inserted readings as Server-Sent Events, optionally filtered by patient
+// bluetooth_id. Protected by the same auth middleware as GET /readings.
+#[utoipa::path(
+    get,
+    path = "/readings/stream",
+    params(StreamQueryParam),
+    responses(
+        (status = 200, description = "A text/event-stream of newly-inserted DBReading frames")
+    )
+)]
+#[get("/readings/stream")]
+pub async fn get_readings_stream(
+    broadcaster: web::Data<ReadingsBroadcaster>,
+    query: web::Query<StreamQueryParam>,
+) -> HttpResponse {
+    let rx = broadcaster.subscribe();
+    let patient_filter = query.patient.clone();
+
+    let body = stream::unfold((rx, patient_filter), |(mut rx, patient_filter)| async move {
+        loop {
+            match rx.recv().await {
+                Ok(reading) => {
+                    if let Some(bid) = &patient_filter {
+                        if &reading.patient.bluetooth_id != bid {
+                            continue;
+                        }
+                    }
+
+                    let json = match serde_json::to_string(&reading) {
+                        Ok(json) => json,
+                        Err(_) => continue,
+                    };
+
+                    let frame = web::Bytes::from(format!("data: {}\n\n", json));
+                    return Some((Ok::<_, actix_web::Error>(frame), (rx, patient_filter)));
+                }
+                // A lagging subscriber just misses the frames it fell behind on.
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "text/event-stream"))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // The Compress middleware skips bodies that already carry a Content-Encoding, so this
+        // opts the stream out of gzip/brotli: compressing would buffer frames waiting for enough
+        // data to encode, defeating the point of pushing them in real time.
+        .insert_header((header::CONTENT_ENCODING, "identity"))
+        .streaming(body)
+}